@@ -0,0 +1,38 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Database schema migrations.
+
+mod v10;
+mod v13;
+
+pub use self::v10::{ToV10, generate_bloom_for_root, check_bloom_exists, check_space_match};
+pub use self::v13::ToV13;
+
+use util::migration::Migration;
+
+/// Migrations applied to bring an Ethereum client database up to the
+/// current schema version, in the order the migration runner must chain
+/// them: `ToV10 -> ToV13`.
+///
+/// `thread_count` is forwarded to `ToV10`, which splits the account bloom
+/// scan across that many worker threads (see `v10::generate_bloom`).
+pub fn chain(thread_count: usize) -> Vec<Box<Migration>> {
+	vec![
+		Box::new(ToV10::with_thread_count(thread_count)),
+		Box::new(ToV13::new()),
+	]
+}
@@ -0,0 +1,72 @@
+// Copyright 2015-2017 Parity Technologies (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Account bloom removal
+
+use std::sync::Arc;
+use db::COL_ACCOUNT_BLOOM;
+use util::migration::{Error, Migration, Progress, Batch, Config};
+use util::Database;
+
+/// Account bloom removal migration. The bloom introduced by `ToV10` is no
+/// longer consumed, so this copies every other column verbatim and drops
+/// the account-bloom column entirely, lowering the column count back down
+/// from the bump `ToV10` made. Chains after `ToV10` (see `migrations::chain`)
+/// in the migration runner; a database already at version 13 is a no-op,
+/// since there is nothing left in the bloom column to drop.
+///
+/// Dropping a column outright rather than remapping the survivors only
+/// preserves the other columns' indices if `COL_ACCOUNT_BLOOM` is the
+/// highest index of the pre-migration 6 (i.e. `ToV10` appended it rather
+/// than inserting it). If `COL_ACCOUNT_BLOOM` is ever allocated a
+/// non-terminal index, this migration needs to shift every column above it
+/// down by one instead of a straight copy.
+#[derive(Default)]
+pub struct ToV13 {
+	progress: Progress,
+}
+
+impl ToV13 {
+	/// New v13 migration
+	pub fn new() -> ToV13 { ToV13 { progress: Progress::default() } }
+}
+
+impl Migration for ToV13 {
+	fn version(&self) -> u32 {
+		13
+	}
+
+	fn pre_columns(&self) -> Option<u32> { Some(6) }
+
+	fn columns(&self) -> Option<u32> { Some(5) }
+
+	fn migrate(&mut self, source: Arc<Database>, config: &Config, dest: &mut Database, col: Option<u32>) -> Result<(), Error> {
+		// Nothing reads the account bloom any more; drop it instead of
+		// copying it forward.
+		if col == COL_ACCOUNT_BLOOM {
+			return Ok(());
+		}
+
+		let mut batch = Batch::new(config, col);
+		for (key, value) in source.iter(col).into_iter().flat_map(|inner| inner) {
+			self.progress.tick();
+			batch.insert(key.into_vec(), value.into_vec(), dest)?;
+		}
+		batch.commit(dest)?;
+
+		Ok(())
+	}
+}